@@ -0,0 +1,611 @@
+//! A [tracing] subscriber for logging to the [systemd journal].
+//!
+//! This crate provides [`Subscriber`], a [`tracing_subscriber::Subscribe`]
+//! which forwards spans and events to `journald` using its native datagram
+//! protocol, so that tools like `journalctl` can filter and display them
+//! alongside every other service's logs.
+//!
+//! [tracing]: https://docs.rs/tracing
+//! [systemd journal]: https://www.freedesktop.org/software/systemd/man/systemd-journald.service.html
+
+#![cfg(target_os = "linux")]
+
+pub mod reader;
+mod writer;
+
+use std::{
+    fmt, io,
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing_core::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record as SpanRecord},
+    Collect, Event, Level,
+};
+use tracing_subscriber::{
+    registry::{LookupSpan, SpanRef},
+    subscribe::{Context, Subscribe},
+};
+
+use writer::{Fallback, ReconnectingWriter};
+
+/// The name of the reserved journal field that carries the human-readable
+/// log message.
+const MESSAGE: &str = "MESSAGE";
+
+/// The name of the reserved journal field that carries the syslog priority.
+const PRIORITY: &str = "PRIORITY";
+
+/// The name of the reserved tracing field that lets an event override its
+/// own `PRIORITY`, bypassing the level-to-priority mapping entirely.
+///
+/// This is how events reach the `EMERG` and `ALERT` priorities, which have
+/// no corresponding [`tracing::Level`].
+const PRIORITY_FIELD: &str = "journald.priority";
+
+/// The name of the reserved tracing field that attaches a [`MessageId`] to
+/// an event, emitted as the journal's `MESSAGE_ID` field.
+const MESSAGE_ID_FIELD: &str = "journald.message_id";
+
+/// A 128-bit `sd-id128` message identifier.
+///
+/// Attaching a `MessageId` to a log site ties every event it produces to an
+/// entry in the systemd message catalog, so operators can filter and key
+/// alerts off `journalctl MESSAGE_ID=...` independent of the free-text
+/// message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageId(u128);
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = ParseMessageIdError;
+
+    /// Parse a message id from its 32 hex-digit string representation.
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        if id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            Ok(MessageId(u128::from_str_radix(id, 16).map_err(|_| ParseMessageIdError(()))?))
+        } else {
+            Err(ParseMessageIdError(()))
+        }
+    }
+}
+
+/// The error returned when parsing a [`MessageId`] from a string that isn't
+/// exactly 32 hexadecimal digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseMessageIdError(());
+
+impl fmt::Display for ParseMessageIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("message id must be exactly 32 hexadecimal digits")
+    }
+}
+
+impl std::error::Error for ParseMessageIdError {}
+
+/// The default prefix added to the name of every non-reserved field, to
+/// avoid accidentally clashing with a reserved journal field such as
+/// `_PID` or `_COMM`.
+const DEFAULT_FIELD_PREFIX: &str = "F";
+
+/// The path to the `journald` native protocol socket.
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The default number of entries kept in memory by [`Subscriber::with_reconnect`]
+/// while the journal socket is unreachable.
+const DEFAULT_BUFFER_CAPACITY: usize = 1024;
+
+/// A [`tracing_subscriber::Subscribe`] that forwards events to the systemd
+/// journal.
+///
+/// Construct one with [`Subscriber::new`] and attach it to a [`Registry`]
+/// with [`CollectExt::with`].
+///
+/// [`Registry`]: tracing_subscriber::Registry
+/// [`CollectExt::with`]: tracing_subscriber::subscribe::CollectExt::with
+pub struct Subscriber {
+    socket: UnixDatagram,
+    socket_path: PathBuf,
+    field_prefix: Option<String>,
+    with_source_location: bool,
+    with_span_fields: bool,
+    priority_mapping: Box<dyn Fn(&Level) -> u8 + Send + Sync>,
+    buffer_capacity: usize,
+    reconnect: bool,
+    fallback: Mutex<Option<Fallback>>,
+    writer: Mutex<Option<ReconnectingWriter>>,
+}
+
+impl Subscriber {
+    /// Construct a subscriber that connects to the systemd journal socket.
+    ///
+    /// Returns an error if the socket at `/run/systemd/journal/socket`
+    /// cannot be opened, e.g. because the system is not running systemd.
+    pub fn new() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNALD_SOCKET)?;
+        Ok(Self {
+            socket,
+            socket_path: PathBuf::from(JOURNALD_SOCKET),
+            field_prefix: Some(DEFAULT_FIELD_PREFIX.to_string()),
+            with_source_location: true,
+            with_span_fields: false,
+            priority_mapping: Box::new(level_to_priority),
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            reconnect: false,
+            fallback: Mutex::new(None),
+            writer: Mutex::new(None),
+        })
+    }
+
+    /// Set the prefix added to the name of every field that isn't already
+    /// a reserved journal field.
+    ///
+    /// Pass `None` to emit field names verbatim, without any prefix.
+    pub fn with_field_prefix(self, field_prefix: Option<String>) -> Self {
+        Self {
+            field_prefix,
+            ..self
+        }
+    }
+
+    /// Whether to emit `CODE_FILE`, `CODE_LINE`, `CODE_FUNC` and `TARGET`
+    /// fields describing the source location of each event.
+    ///
+    /// Enabled by default, since `journalctl -o verbose` and the catalog
+    /// tooling both understand these well-known fields.
+    pub fn with_source_location(self, with_source_location: bool) -> Self {
+        Self {
+            with_source_location,
+            ..self
+        }
+    }
+
+    /// Set the mapping from a [`tracing::Level`] to a syslog `PRIORITY`.
+    ///
+    /// The default mapping follows the usual syslog convention (see
+    /// [`level_to_priority`]); use this to match a different scale, such as
+    /// the one used by the `libsystemd` crate's `Priority` type.
+    pub fn with_priority_mapping(self, mapping: impl Fn(&Level) -> u8 + Send + Sync + 'static) -> Self {
+        Self {
+            priority_mapping: Box::new(mapping),
+            ..self
+        }
+    }
+
+    /// Whether to flatten the fields of every currently-entered span into
+    /// each event, and additionally emit synthetic journal entries on span
+    /// enter and close carrying the span name and, on close, a `busy`/`idle`
+    /// duration breakdown.
+    ///
+    /// Disabled by default. Enable this to get the contextual fields
+    /// (request ids, connection ids, ...) that `#[instrument]`-style
+    /// instrumentation produces into the journal.
+    pub fn with_span_fields(self, with_span_fields: bool) -> Self {
+        Self {
+            with_span_fields,
+            ..self
+        }
+    }
+
+    /// Keep up to `capacity` undelivered entries in memory instead of
+    /// dropping them when the journal socket is unreachable. Pass `0` to
+    /// retry/reconnect without ever buffering.
+    ///
+    /// Only takes effect when combined with [`with_reconnect`](Self::with_reconnect);
+    /// can be called in any order relative to it.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = capacity;
+        self
+    }
+
+    /// Call `fallback` with each raw, already-encoded entry instead of
+    /// buffering it in memory when the journal socket is unreachable.
+    ///
+    /// A common choice is to forward the entry to the legacy `/dev/log`
+    /// syslog socket. Only takes effect when combined with
+    /// [`with_reconnect`](Self::with_reconnect); can be called in any order
+    /// relative to it.
+    pub fn with_fallback(self, fallback: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
+        *self.fallback.lock().unwrap() = Some(Box::new(fallback));
+        self
+    }
+
+    /// Buffer entries in memory and retry delivery in the background
+    /// instead of silently dropping them when `send`ing to the journal
+    /// socket fails, e.g. because `systemd-journald` is restarting.
+    ///
+    /// The background thread re-opens the socket as needed and retries
+    /// with a 100ms backoff; see [`with_buffer_capacity`](Self::with_buffer_capacity)
+    /// and [`with_fallback`](Self::with_fallback) for what happens to
+    /// entries while the socket is down. This can be combined with those
+    /// two builder methods in any order — the writer is only actually
+    /// built, using whatever capacity/fallback were configured by then,
+    /// the first time an entry is sent.
+    pub fn with_reconnect(mut self, enabled: bool) -> Self {
+        self.reconnect = enabled;
+        self
+    }
+
+    /// Get (building it on first use, if enabled) the writer entries are
+    /// sent through when reconnect support is turned on.
+    fn reconnecting_writer(&self) -> std::sync::MutexGuard<'_, Option<ReconnectingWriter>> {
+        let mut writer = self.writer.lock().unwrap();
+        if writer.is_none() {
+            if let Ok(socket) = self.socket.try_clone() {
+                let fallback = self.fallback.lock().unwrap().take();
+                *writer = Some(ReconnectingWriter::new(
+                    socket,
+                    self.socket_path.clone(),
+                    self.buffer_capacity,
+                    fallback,
+                ));
+            }
+        }
+        writer
+    }
+
+    fn send_payload(&self, payload: &[u8]) {
+        if self.reconnect {
+            if let Some(writer) = self.reconnecting_writer().as_ref() {
+                writer.send(payload);
+                return;
+            }
+        }
+        let _ = self.socket.send(payload);
+    }
+}
+
+impl<C> Subscribe<C> for Subscriber
+where
+    C: Collect + for<'lookup> LookupSpan<'lookup>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, C>) {
+        if !self.with_span_fields {
+            return;
+        }
+        let span = ctx.span(id).expect("span must exist in registry");
+        let mut buf = Vec::new();
+        attrs.record(&mut FieldWriter {
+            buf: &mut buf,
+            prefix: self.field_prefix.as_deref(),
+        });
+        let mut extensions = span.extensions_mut();
+        extensions.insert(SpanFields(buf));
+        extensions.insert(Timings::new());
+    }
+
+    fn on_record(&self, id: &Id, values: &SpanRecord<'_>, ctx: Context<'_, C>) {
+        if !self.with_span_fields {
+            return;
+        }
+        let span = ctx.span(id).expect("span must exist in registry");
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut FieldWriter {
+                buf: &mut fields.0,
+                prefix: self.field_prefix.as_deref(),
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, C>) {
+        if !self.with_span_fields {
+            return;
+        }
+        let span = ctx.span(id).expect("span must exist in registry");
+        {
+            let mut extensions = span.extensions_mut();
+            if let Some(timings) = extensions.get_mut::<Timings>() {
+                let now = Instant::now();
+                timings.idle += now.saturating_duration_since(timings.last);
+                timings.last = now;
+            }
+        }
+        self.emit_span_lifecycle_event(&span, "enter", None);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, C>) {
+        if !self.with_span_fields {
+            return;
+        }
+        let span = ctx.span(id).expect("span must exist in registry");
+        let mut extensions = span.extensions_mut();
+        if let Some(timings) = extensions.get_mut::<Timings>() {
+            let now = Instant::now();
+            timings.busy += now.saturating_duration_since(timings.last);
+            timings.last = now;
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, C>) {
+        if !self.with_span_fields {
+            return;
+        }
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        // `busy` and `idle` are accumulated as the span is entered and
+        // exited; a span that was never entered simply reports zero busy
+        // time instead of having its whole lifetime misattributed here.
+        let durations = span
+            .extensions()
+            .get::<Timings>()
+            .map(|timings| (timings.busy, timings.idle));
+        self.emit_span_lifecycle_event(&span, "close", durations);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, C>) {
+        let mut buf = Vec::new();
+        let meta = event.metadata();
+
+        let mut priority_override = PriorityOverride(None);
+        event.record(&mut priority_override);
+        let priority = priority_override
+            .0
+            .unwrap_or_else(|| (self.priority_mapping)(meta.level()));
+        put_field_wellformed(&mut buf, PRIORITY, priority.to_string().as_bytes());
+
+        if self.with_source_location {
+            if let Some(file) = meta.file() {
+                put_field_wellformed(&mut buf, "CODE_FILE", file.as_bytes());
+            }
+            if let Some(line) = meta.line() {
+                put_field_wellformed(&mut buf, "CODE_LINE", line.to_string().as_bytes());
+            }
+            if let Some(module_path) = meta.module_path() {
+                put_field_wellformed(&mut buf, "CODE_FUNC", module_path.as_bytes());
+            }
+            put_field_wellformed(&mut buf, "TARGET", meta.target().as_bytes());
+        }
+
+        let mut message_id = MessageIdOverride(None);
+        event.record(&mut message_id);
+        if let Some(id) = message_id.0.and_then(|id| MessageId::from_str(&id).ok()) {
+            put_field_wellformed(&mut buf, "MESSAGE_ID", id.to_string().as_bytes());
+        }
+
+        let mut writer = FieldWriter {
+            buf: &mut buf,
+            prefix: self.field_prefix.as_deref(),
+        };
+        event.record(&mut writer);
+
+        if self.with_span_fields {
+            if let Some(scope) = ctx.event_scope(event) {
+                for span in scope.from_root() {
+                    if let Some(fields) = span.extensions().get::<SpanFields>() {
+                        buf.extend_from_slice(&fields.0);
+                    }
+                }
+            }
+        }
+
+        self.send_payload(&buf);
+    }
+}
+
+/// The accumulated journal fields recorded on a span, stored in the span's
+/// [extensions](tracing_subscriber::registry::Extensions) so they can be
+/// flattened into every event the span encloses.
+struct SpanFields(Vec<u8>);
+
+/// Tracks how long a span has spent executing (`busy`) versus waiting to be
+/// entered again (`idle`), the same accounting `tracing_subscriber`'s `fmt`
+/// layer uses for its `time.busy`/`time.idle` fields.
+struct Timings {
+    idle: Duration,
+    busy: Duration,
+    last: Instant,
+}
+
+impl Timings {
+    fn new() -> Self {
+        Self {
+            idle: Duration::ZERO,
+            busy: Duration::ZERO,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl Subscriber {
+    /// Emit a synthetic journal entry for a span being entered or closed,
+    /// carrying the span's name, accumulated fields, and — on close — its
+    /// `busy`/`idle` duration breakdown.
+    fn emit_span_lifecycle_event<C>(
+        &self,
+        span: &SpanRef<'_, C>,
+        event: &str,
+        durations: Option<(Duration, Duration)>,
+    ) where
+        C: for<'lookup> LookupSpan<'lookup>,
+    {
+        let mut buf = Vec::new();
+        put_field_wellformed(
+            &mut buf,
+            PRIORITY,
+            (self.priority_mapping)(&Level::TRACE).to_string().as_bytes(),
+        );
+        put_field_wellformed(
+            &mut buf,
+            MESSAGE,
+            format!("{} {}", span.name(), event).as_bytes(),
+        );
+        put_field_wellformed(&mut buf, "SPAN_NAME", span.name().as_bytes());
+        put_field_wellformed(&mut buf, "SPAN_EVENT", event.as_bytes());
+        if let Some((busy, idle)) = durations {
+            put_field_wellformed(&mut buf, "SPAN_BUSY_US", busy.as_micros().to_string().as_bytes());
+            put_field_wellformed(&mut buf, "SPAN_IDLE_US", idle.as_micros().to_string().as_bytes());
+        }
+        if let Some(fields) = span.extensions().get::<SpanFields>() {
+            buf.extend_from_slice(&fields.0);
+        }
+        self.send_payload(&buf);
+    }
+}
+
+/// Translate a [`tracing::Level`] into a syslog `PRIORITY` value, using the
+/// usual mapping between the five tracing levels and the eight syslog levels
+/// journald understands (`EMERG`, `ALERT` and `CRIT` have no tracing
+/// equivalent).
+fn level_to_priority(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 5,
+        Level::DEBUG => 6,
+        Level::TRACE => 7,
+    }
+}
+
+/// A [`Visit`] that looks for the reserved [`PRIORITY_FIELD`] among an
+/// event's fields, ignoring everything else.
+struct PriorityOverride(Option<u8>);
+
+impl Visit for PriorityOverride {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == PRIORITY_FIELD {
+            self.0 = u8::try_from(value).ok().filter(|p| *p <= 7);
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == PRIORITY_FIELD {
+            self.0 = u8::try_from(value).ok().filter(|p| *p <= 7);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// A [`Visit`] that looks for the reserved [`MESSAGE_ID_FIELD`] among an
+/// event's fields, ignoring everything else.
+struct MessageIdOverride(Option<String>);
+
+impl Visit for MessageIdOverride {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == MESSAGE_ID_FIELD {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == MESSAGE_ID_FIELD {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// Write a journal field whose name is already known to be well-formed.
+///
+/// Journal fields are either `NAME=value\n`, for values without an embedded
+/// newline, or `NAME\n` followed by the little-endian length of `value` as
+/// a `u64` and then the raw bytes of `value` themselves. For every field,
+/// the message included, we use the latter encoding only when `value`
+/// contains a newline, and the former otherwise.
+fn put_field_wellformed(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        buf.extend_from_slice(value);
+        buf.push(b'\n');
+    } else {
+        buf.push(b'=');
+        buf.extend_from_slice(value);
+        buf.push(b'\n');
+    }
+}
+
+/// Sanitize a tracing field name into a valid journal field name.
+///
+/// Journal field names may only contain uppercase ASCII letters, digits
+/// and underscores, and must not start with an underscore (those are
+/// reserved for fields set by journald itself). We uppercase the name and
+/// replace every other invalid character with an underscore.
+fn sanitize_field_name(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => sanitized.push(c.to_ascii_uppercase()),
+            _ => sanitized.push('_'),
+        }
+    }
+    while sanitized.starts_with('_') {
+        sanitized.remove(0);
+    }
+    if sanitized.is_empty() {
+        sanitized.push_str("FIELD");
+    }
+    sanitized
+}
+
+/// A [`Visit`] implementation that writes every recorded field into the
+/// native-protocol payload for a single journal entry, renaming `message`
+/// to the reserved `MESSAGE` field.
+struct FieldWriter<'a> {
+    buf: &'a mut Vec<u8>,
+    prefix: Option<&'a str>,
+}
+
+impl<'a> FieldWriter<'a> {
+    fn field_name(&self, field: &Field) -> String {
+        if field.name() == "message" {
+            return MESSAGE.to_string();
+        }
+        let sanitized = sanitize_field_name(field.name());
+        match self.prefix {
+            Some(prefix) => format!("{}_{}", prefix, sanitized),
+            None => sanitized,
+        }
+    }
+
+    fn record(&mut self, field: &Field, value: &[u8]) {
+        // `journald.priority` is consumed by the subscriber to override
+        // `PRIORITY` and must not also show up as a regular field.
+        if field.name() == PRIORITY_FIELD || field.name() == MESSAGE_ID_FIELD {
+            return;
+        }
+        let name = self.field_name(field);
+        put_field_wellformed(self.buf, &name, value);
+    }
+}
+
+impl<'a> Visit for FieldWriter<'a> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, value.to_string().as_bytes());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, value.to_string().as_bytes());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, value.to_string().as_bytes());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, value.to_string().as_bytes());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        // Strings are written in their `Debug` representation, quotes included.
+        self.record(field, format!("{:?}", value).as_bytes());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{:?}", value).as_bytes());
+    }
+}
@@ -0,0 +1,102 @@
+//! A decoder for the systemd journal *native/export* wire format.
+//!
+//! This is the same format [`crate::Subscriber`] writes to the journal
+//! socket, so this module lets the crate's own encoder be tested for
+//! roundtrip correctness without shelling out to `journalctl`.
+//!
+//! Entries are newline-separated field sets. A field is either
+//! `NAME=value\n` for values with no embedded newline, or `NAME\n` followed
+//! by a little-endian `u64` byte length, then exactly that many raw bytes,
+//! then a terminating `\n`. An entry ends at a blank line.
+
+use std::io;
+
+/// Decode every entry in `input`.
+///
+/// Returns an error if `input` ends in the middle of a field, or a
+/// length-prefixed field's declared length runs past the end of `input`.
+pub fn decode_entries(mut input: &[u8]) -> io::Result<Vec<Vec<(String, Vec<u8>)>>> {
+    let mut entries = Vec::new();
+    while !input.is_empty() {
+        let (entry, rest) = decode_entry(input)?;
+        entries.push(entry);
+        input = rest;
+    }
+    Ok(entries)
+}
+
+/// Decode a single entry from the start of `input`, returning it along with
+/// whatever of `input` comes after it.
+fn decode_entry(mut input: &[u8]) -> io::Result<(Vec<(String, Vec<u8>)>, &[u8])> {
+    let mut fields = Vec::new();
+    loop {
+        match input.first() {
+            None => break,
+            Some(b'\n') => {
+                input = &input[1..];
+                break;
+            }
+            Some(_) => {
+                let (field, rest) = decode_field(input)?;
+                fields.push(field);
+                input = rest;
+            }
+        }
+    }
+    Ok((fields, input))
+}
+
+/// Decode a single field from the start of `input`, returning it along with
+/// whatever of `input` comes after it.
+///
+/// The length-prefixed encoding is the whole reason this parser exists: a
+/// binary value may itself contain a `\n`, so once we know we're in that
+/// branch we must consume exactly the declared number of bytes rather than
+/// scanning for the next newline.
+fn decode_field(input: &[u8]) -> io::Result<((String, Vec<u8>), &[u8])> {
+    let newline = find_newline(input)?;
+    let line = &input[..newline];
+    let rest = &input[newline + 1..];
+
+    match line.iter().position(|&b| b == b'=') {
+        Some(eq) => {
+            let name = field_name(&line[..eq])?;
+            let value = line[eq + 1..].to_vec();
+            Ok(((name, value), rest))
+        }
+        None => {
+            let name = field_name(line)?;
+            let (len_bytes, rest) = split_at_checked(rest, 8)?;
+            let len = u64::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+            let (value, rest) = split_at_checked(rest, len)?;
+            let (newline, rest) = split_at_checked(rest, 1)?;
+            if newline != b"\n" {
+                return Err(invalid_data("length-prefixed field missing terminating newline"));
+            }
+            Ok(((name, value.to_vec()), rest))
+        }
+    }
+}
+
+fn find_newline(input: &[u8]) -> io::Result<usize> {
+    input
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| invalid_data("truncated field: missing newline"))
+}
+
+fn field_name(bytes: &[u8]) -> io::Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data("field name is not valid UTF-8"))
+}
+
+fn split_at_checked(input: &[u8], mid: usize) -> io::Result<(&[u8], &[u8])> {
+    if mid > input.len() {
+        Err(invalid_data("truncated field: declared length runs past end of input"))
+    } else {
+        Ok(input.split_at(mid))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,256 @@
+//! A journal socket writer that tolerates `systemd-journald` restarts.
+//!
+//! [`Subscriber::with_reconnect`](crate::Subscriber::with_reconnect) switches
+//! the subscriber from writing straight to its [`UnixDatagram`] to using a
+//! [`ReconnectingWriter`], which keeps a bounded ring of undelivered entries
+//! and retries delivery from a background thread, re-opening the socket
+//! whenever it looks like `journald` has restarted.
+
+use std::{
+    collections::VecDeque,
+    io,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the background thread retries delivery of buffered entries.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A closure invoked with a raw, already-encoded journal entry when the
+/// native socket protocol is unavailable, e.g. to forward it to `/dev/log`.
+pub(crate) type Fallback = Box<dyn Fn(&[u8]) + Send + Sync>;
+
+struct Inner {
+    path: PathBuf,
+    socket: Mutex<Option<UnixDatagram>>,
+    buffer: Mutex<VecDeque<Vec<u8>>>,
+    capacity: usize,
+    fallback: Option<Fallback>,
+    shutdown: AtomicBool,
+    wake_lock: Mutex<()>,
+    wake: Condvar,
+}
+
+/// Buffers entries in memory and retries delivery to the journal socket in
+/// the background, instead of silently dropping them the moment a `send`
+/// fails.
+pub(crate) struct ReconnectingWriter {
+    inner: Arc<Inner>,
+    flush_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ReconnectingWriter {
+    /// Wrap an already-connected socket, keeping up to `capacity` entries
+    /// in memory when delivery fails, and falling back to `fallback` (if
+    /// given) instead of buffering.
+    pub(crate) fn new(socket: UnixDatagram, path: PathBuf, capacity: usize, fallback: Option<Fallback>) -> Self {
+        let inner = Arc::new(Inner {
+            path,
+            socket: Mutex::new(Some(socket)),
+            buffer: Mutex::new(VecDeque::new()),
+            capacity,
+            fallback,
+            shutdown: AtomicBool::new(false),
+            wake_lock: Mutex::new(()),
+            wake: Condvar::new(),
+        });
+        let flush_inner = Arc::clone(&inner);
+        let flush_thread = thread::Builder::new()
+            .name("tracing-journald-flush".into())
+            .spawn(move || flush_loop(&flush_inner))
+            .ok();
+        Self { inner, flush_thread }
+    }
+
+    /// Send `payload`, buffering it for later retry if delivery fails.
+    pub(crate) fn send(&self, payload: &[u8]) {
+        if self.inner.try_send(payload) {
+            return;
+        }
+        self.inner.buffer_or_fall_back(payload);
+        self.wake_flusher();
+    }
+
+    fn wake_flusher(&self) {
+        let _guard = self.inner.wake_lock.lock().unwrap();
+        self.inner.wake.notify_one();
+    }
+}
+
+impl Drop for ReconnectingWriter {
+    fn drop(&mut self) {
+        self.inner.shutdown.store(true, Ordering::SeqCst);
+        self.wake_flusher();
+        if let Some(handle) = self.flush_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Inner {
+    /// Try to deliver `payload` over the current socket, reconnecting once
+    /// if the socket is missing or the send fails in a way that suggests
+    /// `journald` has restarted.
+    fn try_send(&self, payload: &[u8]) -> bool {
+        let mut guard = self.socket.lock().unwrap();
+        if let Some(socket) = guard.as_ref() {
+            match socket.send(payload) {
+                Ok(_) => return true,
+                Err(e) if is_reconnectable(&e) => {}
+                Err(_) => return false,
+            }
+        }
+        match connect(&self.path) {
+            Ok(socket) => {
+                let delivered = socket.send(payload).is_ok();
+                *guard = Some(socket);
+                delivered
+            }
+            Err(_) => {
+                *guard = None;
+                false
+            }
+        }
+    }
+
+    fn buffer_or_fall_back(&self, payload: &[u8]) {
+        if let Some(fallback) = &self.fallback {
+            fallback(payload);
+            return;
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() >= self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(payload.to_vec());
+    }
+}
+
+/// Open a fresh datagram socket connected to the journal at `path`.
+pub(crate) fn connect(path: &Path) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+/// Whether `error` looks like the journal socket going away, rather than a
+/// permanent or payload-related failure worth giving up on immediately.
+fn is_reconnectable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn flush_loop(inner: &Arc<Inner>) {
+    while !inner.shutdown.load(Ordering::SeqCst) {
+        let next = inner.buffer.lock().unwrap().front().cloned();
+        match next {
+            Some(payload) if inner.try_send(&payload) => {
+                inner.buffer.lock().unwrap().pop_front();
+            }
+            _ => {
+                let guard = inner.wake_lock.lock().unwrap();
+                let _ = inner.wake.wait_timeout(guard, RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Pick a socket path under the system temp dir unique to this test run.
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "tracing-journald-writer-test-{}-{}-{}.sock",
+            name,
+            std::process::id(),
+            std::ptr::addr_of!(name) as usize
+        ))
+    }
+
+    /// Block until `socket` has a datagram ready to read, or panic after
+    /// `timeout` elapses.
+    fn recv_within(socket: &UnixDatagram, timeout: Duration) -> Vec<u8> {
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        let mut buf = [0u8; 1024];
+        let len = socket
+            .recv(&mut buf)
+            .expect("expected a datagram before the timeout");
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn buffers_and_redelivers_after_journald_restarts() {
+        let path = socket_path("buffers_and_redelivers_after_journald_restarts");
+        let _ = std::fs::remove_file(&path);
+
+        // Stand in for journald: a listener bound at `path`, plus a client
+        // socket connected to it the way `Subscriber::new` connects to the
+        // real journal socket.
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let client = connect(&path).unwrap();
+        let writer = ReconnectingWriter::new(client, path.clone(), 4, None);
+
+        // Simulate journald going away: unbind the listener so sends start
+        // failing, the same way they would if journald restarted.
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        writer.send(b"while journald is down\n");
+
+        // No listener exists yet, so the entry must have been buffered
+        // rather than delivered or dropped.
+        assert_eq!(writer.inner.buffer.lock().unwrap().len(), 1);
+
+        // Bring journald back by rebinding a listener at the same path, and
+        // give the background flush thread a chance to reconnect and
+        // redeliver the buffered entry.
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let delivered = recv_within(&listener, Duration::from_secs(2));
+        assert_eq!(delivered, b"while journald is down\n");
+
+        let start = Instant::now();
+        while !writer.inner.buffer.lock().unwrap().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(2), "buffered entry was never drained");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn zero_capacity_drops_instead_of_buffering() {
+        let path = socket_path("zero_capacity_drops_instead_of_buffering");
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let client = connect(&path).unwrap();
+        let writer = ReconnectingWriter::new(client, path.clone(), 0, None);
+
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        writer.send(b"dropped while journald is down\n");
+        writer.send(b"also dropped\n");
+
+        // With capacity 0 nothing should ever accumulate in the buffer, not
+        // even transiently via an evict-then-push that leaves one stale
+        // entry behind.
+        assert_eq!(writer.inner.buffer.lock().unwrap().len(), 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -16,9 +16,14 @@ fn journalctl_version() -> std::io::Result<String> {
 }
 
 fn with_journald(f: impl FnOnce()) {
+    with_configured_journald(|subscriber| subscriber, f)
+}
+
+fn with_configured_journald(configure: impl FnOnce(Subscriber) -> Subscriber, f: impl FnOnce()) {
     match journalctl_version() {
         Ok(_) => {
-            let sub = Registry::default().with(Subscriber::new().unwrap().with_field_prefix(None));
+            let subscriber = configure(Subscriber::new().unwrap().with_field_prefix(None));
+            let sub = Registry::default().with(subscriber);
             tracing::collect::with_default(sub, f);
         }
         Err(error) => eprintln!(
@@ -160,6 +165,203 @@ fn multiline_message_trailing_newline() {
     });
 }
 
+#[test]
+fn source_location() {
+    with_journald(|| {
+        let line = line!() + 1;
+        info!(test.name = "source_location", "Hello World");
+
+        let message = retry_read_one_line_from_journal("source_location");
+        assert_eq!(message["MESSAGE"], "Hello World");
+        assert_eq!(message["CODE_LINE"], line.to_string().as_str());
+        assert_eq!(message["TARGET"], "journal");
+    });
+}
+
+#[test]
+fn custom_priority_mapping() {
+    with_configured_journald(
+        |subscriber| subscriber.with_priority_mapping(|_level| 2),
+        || {
+            info!(test.name = "custom_priority_mapping", "Hello World");
+
+            let message = retry_read_one_line_from_journal("custom_priority_mapping");
+            assert_eq!(message["PRIORITY"], "2");
+        },
+    );
+}
+
+#[test]
+fn priority_override() {
+    with_journald(|| {
+        info!(
+            test.name = "priority_override",
+            journald.priority = 1,
+            "Hello World"
+        );
+
+        let message = retry_read_one_line_from_journal("priority_override");
+        assert_eq!(message["PRIORITY"], "1");
+        assert!(!message.contains_key("JOURNALD_PRIORITY"));
+    });
+}
+
+#[test]
+fn priority_override_out_of_range_falls_back_to_mapping() {
+    with_configured_journald(
+        |subscriber| subscriber.with_priority_mapping(|_level| 2),
+        || {
+            info!(
+                test.name = "priority_override_out_of_range_falls_back_to_mapping",
+                journald.priority = 1000,
+                "Hello World"
+            );
+
+            let message =
+                retry_read_one_line_from_journal("priority_override_out_of_range_falls_back_to_mapping");
+            assert_eq!(message["PRIORITY"], "2");
+            assert!(!message.contains_key("JOURNALD_PRIORITY"));
+        },
+    );
+}
+
+#[test]
+fn priority_override_does_not_leak_with_default_field_prefix() {
+    with_configured_journald(
+        |subscriber| subscriber.with_field_prefix(Some("F".to_string())),
+        || {
+            info!(
+                test.name = "priority_override_does_not_leak_with_default_field_prefix",
+                journald.priority = 1,
+                "Hello World"
+            );
+
+            let message =
+                retry_read_one_line_from_journal("priority_override_does_not_leak_with_default_field_prefix");
+            assert_eq!(message["PRIORITY"], "1");
+            assert!(!message.contains_key("F_JOURNALD_PRIORITY"));
+        },
+    );
+}
+
+#[test]
+fn message_id() {
+    with_journald(|| {
+        info!(
+            test.name = "message_id",
+            journald.message_id = "d25e457b84ee4d9db7bc131b671055d7",
+            "Hello World"
+        );
+
+        let message = retry_read_one_line_from_journal("message_id");
+        assert_eq!(message["MESSAGE_ID"], "d25e457b84ee4d9db7bc131b671055d7");
+    });
+}
+
+#[test]
+fn span_fields() {
+    with_configured_journald(
+        |subscriber| subscriber.with_span_fields(true),
+        || {
+            let span = tracing::info_span!("request", request_id = 42);
+            let _guard = span.enter();
+
+            info!(test.name = "span_fields", "Hello World");
+
+            let message = retry_read_one_line_from_journal("span_fields");
+            assert_eq!(message["MESSAGE"], "Hello World");
+            assert_eq!(message["REQUEST_ID"], "42");
+        },
+    );
+}
+
+/// Find and return the first span-close entry for `test_name`, retrying
+/// since the close entry may take a moment to land after the span is
+/// dropped.
+fn retry_find_span_close(test_name: &str) -> HashMap<String, Field> {
+    retry(|| {
+        read_from_journal(test_name)
+            .into_iter()
+            .find(|message| matches!(message.get("SPAN_EVENT"), Some(Field::Text(event)) if event == "close"))
+            .ok_or_else(|| "no span close entry yet".to_string())
+    })
+    .unwrap()
+}
+
+fn field_as_micros(field: &Field) -> u64 {
+    match field {
+        Field::Text(s) => s.parse().expect("duration field should be an integer"),
+        Field::Binary(_) => panic!("expected a text field"),
+    }
+}
+
+#[test]
+fn span_busy_idle_accounting() {
+    with_configured_journald(
+        |subscriber| subscriber.with_span_fields(true),
+        || {
+            let span = tracing::info_span!("busy_idle", test.name = "span_busy_idle_accounting");
+
+            {
+                let _guard = span.enter();
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+            {
+                let _guard = span.enter();
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            drop(span);
+
+            let close = retry_find_span_close("span_busy_idle_accounting");
+            let busy_us = field_as_micros(&close["SPAN_BUSY_US"]);
+            let idle_us = field_as_micros(&close["SPAN_IDLE_US"]);
+
+            // Entered twice for ~50ms each: busy should reflect both
+            // intervals, not just the last one.
+            assert!(busy_us >= 80_000, "busy_us was {}", busy_us);
+            // Exited for ~50ms between the two enters.
+            assert!(idle_us >= 30_000, "idle_us was {}", idle_us);
+        },
+    );
+}
+
+#[test]
+fn span_lifecycle_events_honor_custom_priority_mapping() {
+    with_configured_journald(
+        |subscriber| {
+            subscriber
+                .with_span_fields(true)
+                .with_priority_mapping(|_level| 2)
+        },
+        || {
+            let span = tracing::info_span!(
+                "custom_priority_span",
+                test.name = "span_lifecycle_events_honor_custom_priority_mapping"
+            );
+            let _guard = span.enter();
+            drop(_guard);
+            drop(span);
+
+            let close = retry_find_span_close("span_lifecycle_events_honor_custom_priority_mapping");
+            assert_eq!(close["PRIORITY"], "2");
+        },
+    );
+}
+
+#[test]
+fn reconnecting_writer_still_delivers() {
+    with_configured_journald(
+        |subscriber| subscriber.with_reconnect(true).with_buffer_capacity(4),
+        || {
+            info!(test.name = "reconnecting_writer_still_delivers", "Hello World");
+
+            let message = retry_read_one_line_from_journal("reconnecting_writer_still_delivers");
+            assert_eq!(message["MESSAGE"], "Hello World");
+        },
+    );
+}
+
 #[test]
 fn internal_null_byte() {
     with_journald(|| {
@@ -0,0 +1,94 @@
+#![cfg(target_os = "linux")]
+
+use tracing_journald::reader::decode_entries;
+
+/// Encode a single field using the native/export wire format, mirroring
+/// what `tracing_journald::Subscriber` writes to the journal socket.
+fn encode_field(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        buf.extend_from_slice(value);
+        buf.push(b'\n');
+    } else {
+        buf.push(b'=');
+        buf.extend_from_slice(value);
+        buf.push(b'\n');
+    }
+}
+
+#[test]
+fn roundtrip_multiline_value() {
+    let mut buf = Vec::new();
+    encode_field(&mut buf, "MESSAGE", b"Hello\nMultiline\nWorld");
+    buf.push(b'\n');
+
+    let entries = decode_entries(&buf).unwrap();
+    assert_eq!(
+        entries,
+        vec![vec![(
+            "MESSAGE".to_string(),
+            b"Hello\nMultiline\nWorld".to_vec()
+        )]]
+    );
+}
+
+#[test]
+fn roundtrip_value_with_trailing_newline() {
+    // The critical edge case: a length-prefixed value that itself ends in
+    // `\n` must not be confused with the entry's terminating blank line.
+    let mut buf = Vec::new();
+    encode_field(&mut buf, "MESSAGE", b"A trailing newline\n");
+    buf.push(b'\n');
+
+    let entries = decode_entries(&buf).unwrap();
+    assert_eq!(
+        entries,
+        vec![vec![(
+            "MESSAGE".to_string(),
+            b"A trailing newline\n".to_vec()
+        )]]
+    );
+}
+
+#[test]
+fn roundtrip_null_byte_value() {
+    let mut buf = Vec::new();
+    encode_field(&mut buf, "MESSAGE", b"An internal\x00byte");
+    buf.push(b'\n');
+
+    let entries = decode_entries(&buf).unwrap();
+    assert_eq!(
+        entries,
+        vec![vec![("MESSAGE".to_string(), b"An internal\x00byte".to_vec())]]
+    );
+}
+
+#[test]
+fn decodes_multiple_entries() {
+    let mut buf = Vec::new();
+    encode_field(&mut buf, "MESSAGE", b"first");
+    buf.push(b'\n');
+    encode_field(&mut buf, "MESSAGE", b"second");
+    buf.push(b'\n');
+
+    let entries = decode_entries(&buf).unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            vec![("MESSAGE".to_string(), b"first".to_vec())],
+            vec![("MESSAGE".to_string(), b"second".to_vec())],
+        ]
+    );
+}
+
+#[test]
+fn rejects_truncated_length_prefixed_field() {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(b"MESSAGE\n");
+    buf.extend_from_slice(&100u64.to_le_bytes());
+    buf.extend_from_slice(b"too short");
+
+    assert!(decode_entries(&buf).is_err());
+}